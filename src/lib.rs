@@ -26,7 +26,6 @@
 // don't actually have an opinion on `from_foo` names.
 #![allow(clippy::wrong_self_convention)]
 
-use std::cmp::Ordering;
 use std::fmt;
 
 /// A distinct number type for line numbers, to prevent confusion with
@@ -85,28 +84,316 @@ pub struct SingleLineSpan {
     pub end_col: u32,
 }
 
+/// A non-ASCII char within a line, recorded so we can convert byte
+/// columns to UTF-16 columns without rescanning the whole line on
+/// every query.
+#[derive(Debug, Clone, Copy)]
+struct Utf16Char {
+    /// Byte offset of this char, relative to the start of its line.
+    byte_pos: u32,
+    /// Length of this char in UTF-8 bytes.
+    len_utf8: u32,
+    /// The UTF-16 column immediately before this char.
+    utf16_col: u32,
+}
+
+/// A char whose UTF-8 encoding is more than one byte long, recorded so
+/// we can convert byte columns to Unicode scalar value (char) columns
+/// without rescanning the string on every query.
+///
+/// This mirrors `rustc_span::SourceFile::multibyte_chars`.
+#[derive(Debug, Clone, Copy)]
+struct MultiByteChar {
+    /// Absolute byte position of this char in the source string.
+    byte_pos: u32,
+    /// Length of this char in UTF-8 bytes.
+    bytes: u8,
+}
+
+/// Does `ch` occupy no columns when displayed, e.g. a combining
+/// mark? This loosely follows the East Asian Width rules used by the
+/// `unicode-width` crate.
+fn is_zero_width(ch: char) -> bool {
+    matches!(ch as u32,
+        0x0300..=0x036F // Combining Diacritical Marks
+        | 0x0483..=0x0489 // Combining Cyrillic
+        | 0x200B..=0x200F // Zero width space, joiners, marks
+        | 0x20D0..=0x20FF // Combining Diacritical Marks for Symbols
+        | 0xFE00..=0xFE0F // Variation Selectors
+        | 0xFE20..=0xFE2F // Combining Half Marks
+    )
+}
+
+/// Does `ch` occupy two columns when displayed, e.g. a CJK
+/// ideograph? This loosely follows the East Asian Width rules used
+/// by the `unicode-width` crate.
+fn is_wide(ch: char) -> bool {
+    matches!(ch as u32,
+        0x1100..=0x115F // Hangul Jamo
+        | 0x2E80..=0x303E // CJK Radicals, Kangxi Radicals, CJK punctuation
+        | 0x3041..=0x33FF // Hiragana .. CJK Compatibility
+        | 0x3400..=0x4DBF // CJK Unified Ideographs Extension A
+        | 0x4E00..=0x9FFF // CJK Unified Ideographs
+        | 0xA000..=0xA4CF // Yi Syllables
+        | 0xAC00..=0xD7A3 // Hangul Syllables
+        | 0xF900..=0xFAFF // CJK Compatibility Ideographs
+        | 0xFF00..=0xFF60 // Fullwidth Forms
+        | 0xFFE0..=0xFFE6 // Fullwidth Signs
+        | 0x1F300..=0x1FAFF // Misc symbols, pictographs, emoji
+        | 0x20000..=0x3FFFD // CJK Unified Ideographs Extension B and beyond
+    )
+}
+
+/// A char that doesn't occupy exactly one terminal column, recorded
+/// so we can convert byte columns to display columns without
+/// rescanning the whole line on every query.
+///
+/// This mirrors `rustc_span::SourceFile::non_narrow_chars`.
+#[derive(Debug, Clone, Copy)]
+enum NonNarrowChar {
+    /// A zero-width char, e.g. a combining mark.
+    ZeroWidth { byte_pos: u32, len_utf8: u32 },
+    /// An East Asian Wide or Fullwidth char, which occupies 2 columns.
+    Wide { byte_pos: u32, len_utf8: u32 },
+    /// A tab, which advances to the next multiple of the tab width.
+    Tab { byte_pos: u32 },
+}
+
+impl NonNarrowChar {
+    fn byte_pos(&self) -> u32 {
+        match *self {
+            NonNarrowChar::ZeroWidth { byte_pos, .. }
+            | NonNarrowChar::Wide { byte_pos, .. }
+            | NonNarrowChar::Tab { byte_pos } => byte_pos,
+        }
+    }
+
+    fn len_utf8(&self) -> u32 {
+        match *self {
+            NonNarrowChar::ZeroWidth { len_utf8, .. } | NonNarrowChar::Wide { len_utf8, .. } => {
+                len_utf8
+            }
+            NonNarrowChar::Tab { .. } => 1,
+        }
+    }
+}
+
 /// A struct for efficiently converting absolute string positions to
 /// line-relative positions.
 #[derive(Debug)]
 pub struct LinePositions {
     /// A vector of the start and end positions (in bytes) of all the
-    /// lines in a string. Positions include the newline character
-    /// itself.
+    /// lines in a string. The end position is the position of the
+    /// line's terminator (`\n`, or the `\r` of a `\r\n` pair), so it
+    /// excludes the terminator itself from the line's content.
     positions: Vec<(usize, usize)>,
+    /// For each line, the non-ASCII chars in that line, in order.
+    /// Empty (and allocation-free) for lines that are entirely ASCII.
+    utf16_chars: Vec<Vec<Utf16Char>>,
+    /// Every multi-byte char in the whole string, in byte position
+    /// order.
+    multibyte_chars: Vec<MultiByteChar>,
+    /// For each line, the chars in that line that aren't exactly one
+    /// column wide, in order. Empty (and allocation-free) for lines
+    /// that only contain narrow chars.
+    non_narrow_chars: Vec<Vec<NonNarrowChar>>,
+}
+
+/// Scan `s` for line terminators, returning the `(start, end)` byte
+/// position of every line. `end` is the position of the line's
+/// terminator (`\n`, or the `\r` of a `\r\n` pair), excluding the
+/// terminator itself from the line's content.
+///
+/// This walks the string's bytes one at a time rather than using
+/// `str::split('\n')`, so it can recognise `\n`, `\r\n` and bare `\r`
+/// (old Mac) terminators without the per-char overhead of iterating
+/// `char`s.
+fn scan_lines(s: &str) -> Vec<(usize, usize)> {
+    let bytes = s.as_bytes();
+    let mut positions = vec![];
+    let mut line_start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => {
+                positions.push((line_start, i));
+                i += 1;
+                line_start = i;
+            }
+            b'\r' => {
+                positions.push((line_start, i));
+                i += if bytes.get(i + 1) == Some(&b'\n') { 2 } else { 1 };
+                line_start = i;
+            }
+            _ => i += 1,
+        }
+    }
+    positions.push((line_start, bytes.len()));
+
+    positions
+}
+
+/// Build the per-line/per-string char caches used to answer
+/// `from_offset_utf16`/`from_offset_chars`/`from_offset_display`
+/// queries, given `s` and its already-computed `positions`.
+fn build_char_caches(
+    s: &str,
+    positions: &[(usize, usize)],
+) -> (Vec<Vec<Utf16Char>>, Vec<MultiByteChar>, Vec<Vec<NonNarrowChar>>) {
+    let utf16_chars = positions
+        .iter()
+        .map(|(line_start, line_end)| {
+            let mut chars = vec![];
+            let mut utf16_col = 0;
+            for (byte_pos, ch) in s[*line_start..*line_end].char_indices() {
+                if !ch.is_ascii() {
+                    chars.push(Utf16Char {
+                        byte_pos: byte_pos as u32,
+                        len_utf8: ch.len_utf8() as u32,
+                        utf16_col,
+                    });
+                }
+                utf16_col += ch.len_utf16() as u32;
+            }
+            chars
+        })
+        .collect();
+
+    let multibyte_chars = s
+        .char_indices()
+        .filter(|(_, ch)| ch.len_utf8() > 1)
+        .map(|(byte_pos, ch)| MultiByteChar {
+            byte_pos: byte_pos as u32,
+            bytes: ch.len_utf8() as u8,
+        })
+        .collect();
+
+    let non_narrow_chars = positions
+        .iter()
+        .map(|(line_start, line_end)| {
+            let mut chars = vec![];
+            for (byte_pos, ch) in s[*line_start..*line_end].char_indices() {
+                let byte_pos = byte_pos as u32;
+                let len_utf8 = ch.len_utf8() as u32;
+                if ch == '\t' {
+                    chars.push(NonNarrowChar::Tab { byte_pos });
+                } else if is_zero_width(ch) {
+                    chars.push(NonNarrowChar::ZeroWidth { byte_pos, len_utf8 });
+                } else if is_wide(ch) {
+                    chars.push(NonNarrowChar::Wide { byte_pos, len_utf8 });
+                }
+            }
+            chars
+        })
+        .collect();
+
+    (utf16_chars, multibyte_chars, non_narrow_chars)
 }
 
 impl From<&str> for LinePositions {
     fn from(s: &str) -> Self {
-        let mut line_start = 0;
-        let mut positions = vec![];
-        for line in s.split('\n') {
-            let line_end = line_start + line.len() + "\n".len();
-            // TODO: this assumes lines terminate with \n, not \r\n.
-            positions.push((line_start, line_end - 1));
-            line_start = line_end;
+        let positions = scan_lines(s);
+        let (utf16_chars, multibyte_chars, non_narrow_chars) = build_char_caches(s, &positions);
+
+        LinePositions {
+            positions,
+            utf16_chars,
+            multibyte_chars,
+            non_narrow_chars,
+        }
+    }
+}
+
+/// An incremental builder for [`LinePositions`], for callers that
+/// receive text in chunks (e.g. reading a large file or a stream)
+/// and don't want to concatenate it all up front.
+///
+/// ```
+/// use line_numbers::LinePositionsBuilder;
+///
+/// let mut builder = LinePositionsBuilder::new();
+/// builder.push_str("foo\n");
+/// builder.push_str("bar\n");
+/// let line_positions = builder.finish();
+/// ```
+#[derive(Debug, Default)]
+pub struct LinePositionsBuilder {
+    buf: String,
+    positions: Vec<(usize, usize)>,
+    line_start: usize,
+    /// Whether the buffer ends with a bare `\r` whose line has
+    /// already been recorded, but which might still turn out to be
+    /// the first half of a `\r\n` pair split across chunks.
+    pending_cr: bool,
+}
+
+impl LinePositionsBuilder {
+    /// Create an empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append more text and extend the line table in place.
+    pub fn push_str(&mut self, chunk: &str) {
+        let mut i = self.buf.len();
+        self.buf.push_str(chunk);
+        let bytes = self.buf.as_bytes();
+
+        if self.pending_cr {
+            // Only resolve this once we actually have a next byte to
+            // look at — an empty `chunk` must leave `pending_cr` set
+            // so a later chunk can still complete the `\r\n` pair.
+            if let Some(&next) = bytes.get(i) {
+                self.pending_cr = false;
+                if next == b'\n' {
+                    // This `\n` completes a `\r\n` pair whose line
+                    // was already recorded when we saw the `\r`.
+                    i += 1;
+                    self.line_start = i;
+                }
+            }
+        }
+
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\n' => {
+                    self.positions.push((self.line_start, i));
+                    i += 1;
+                    self.line_start = i;
+                }
+                b'\r' => {
+                    self.positions.push((self.line_start, i));
+                    if bytes.get(i + 1) == Some(&b'\n') {
+                        i += 2;
+                    } else if i + 1 == bytes.len() {
+                        // We won't know whether this is a `\r\n` pair
+                        // until the next chunk arrives.
+                        self.pending_cr = true;
+                        i += 1;
+                    } else {
+                        i += 1;
+                    }
+                    self.line_start = i;
+                }
+                _ => i += 1,
+            }
         }
+    }
 
-        LinePositions { positions }
+    /// Finish building, returning the completed [`LinePositions`].
+    pub fn finish(mut self) -> LinePositions {
+        self.positions.push((self.line_start, self.buf.len()));
+
+        let (utf16_chars, multibyte_chars, non_narrow_chars) =
+            build_char_caches(&self.buf, &self.positions);
+
+        LinePositions {
+            positions: self.positions,
+            utf16_chars,
+            multibyte_chars,
+            non_narrow_chars,
+        }
     }
 }
 
@@ -127,24 +414,216 @@ impl LinePositions {
             );
         }
 
+        // Find the last line whose start is at or before `offset`,
+        // i.e. the line containing `offset`, using the next line's
+        // start as an exclusive upper bound. This correctly resolves
+        // an offset that falls in a line's terminator (e.g. the `\n`
+        // of a `\r\n` pair), which isn't covered by any line's
+        // `(start, end)` content range.
         let idx = self
             .positions
-            .binary_search_by(|(line_start, line_end)| {
-                if *line_end < offset {
-                    return Ordering::Less;
-                }
-                if *line_start > offset {
-                    return Ordering::Greater;
-                }
+            .partition_point(|(line_start, _)| *line_start <= offset)
+            - 1;
+
+        let (line_start_offset, line_end_offset) = self.positions[idx];
+        // Clamp to the line's content length, so an offset in the
+        // line's terminator resolves to the line's last column
+        // rather than reading past its content.
+        let column = (offset - line_start_offset).min(line_end_offset - line_start_offset);
+
+        (LineNumber::from(idx as u32), column)
+    }
+
+    /// Convert a byte column on `line` to a column measured in UTF-16
+    /// code units, as used by the Language Server Protocol.
+    fn byte_col_to_utf16_col(&self, line: LineNumber, byte_col: usize) -> usize {
+        let chars = &self.utf16_chars[line.as_usize()];
+        let idx = chars.partition_point(|c| (c.byte_pos as usize) <= byte_col);
+
+        match idx.checked_sub(1).map(|i| chars[i]) {
+            // `byte_col` falls inside this char: round down to its start.
+            Some(c) if byte_col < c.byte_pos as usize + c.len_utf8 as usize => {
+                c.utf16_col as usize
+            }
+            // `byte_col` is in the (ASCII) run after this char.
+            Some(c) => {
+                let char_end = c.byte_pos as usize + c.len_utf8 as usize;
+                // A char that needs 4 UTF-8 bytes is astral, and
+                // becomes a UTF-16 surrogate pair (2 code units).
+                // Everything else we record is non-ASCII but still
+                // in the BMP, so it's a single UTF-16 code unit.
+                let char_utf16_len = if c.len_utf8 == 4 { 2 } else { 1 };
+                c.utf16_col as usize + char_utf16_len + (byte_col - char_end)
+            }
+            // No non-ASCII chars before `byte_col` on this line.
+            None => byte_col,
+        }
+    }
+
+    /// Return the line and column corresponding to this `offset`, with
+    /// the column measured in UTF-16 code units rather than bytes.
+    ///
+    /// This is useful for producing LSP `Position` values, as the
+    /// Language Server Protocol addresses columns in UTF-16 code
+    /// units.
+    ///
+    /// If `offset` falls in the middle of a multi-byte UTF-8
+    /// sequence, it is rounded down to the start of that char.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` is out of bounds.
+    pub fn from_offset_utf16(&self, offset: usize) -> (LineNumber, usize) {
+        let (line, byte_col) = self.from_offset(offset);
+        (line, self.byte_col_to_utf16_col(line, byte_col))
+    }
 
-                Ordering::Equal
+    /// Convert this region into line spans, with columns measured in
+    /// UTF-16 code units rather than bytes. See
+    /// [`LinePositions::from_offset_utf16`].
+    ///
+    /// `region_start` and `region_end` are measured in bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `region_start` or `region_end` are out of bounds, or
+    /// if `region_start` is greater than `region_end`.
+    pub fn from_region_utf16(
+        &self,
+        region_start: usize,
+        region_end: usize,
+    ) -> Vec<SingleLineSpan> {
+        self.from_region(region_start, region_end)
+            .into_iter()
+            .map(|span| SingleLineSpan {
+                line: span.line,
+                start_col: self.byte_col_to_utf16_col(span.line, span.start_col as usize) as u32,
+                end_col: self.byte_col_to_utf16_col(span.line, span.end_col as usize) as u32,
             })
-            .expect("line should be present");
+            .collect()
+    }
+
+    /// Return the line and column corresponding to this `offset`, with
+    /// the column measured in Unicode scalar values (chars) rather
+    /// than bytes.
+    ///
+    /// If `offset` falls in the middle of a multi-byte UTF-8
+    /// sequence, it is rounded down to the start of that char.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` is out of bounds.
+    pub fn from_offset_chars(&self, offset: usize) -> (LineNumber, usize) {
+        // If `offset` falls in the middle of a multi-byte char, round
+        // down to the start of that char.
+        let idx = self
+            .multibyte_chars
+            .partition_point(|c| (c.byte_pos as usize) <= offset);
+        let offset = match idx.checked_sub(1).map(|i| self.multibyte_chars[i]) {
+            Some(c) if offset < c.byte_pos as usize + c.bytes as usize => c.byte_pos as usize,
+            _ => offset,
+        };
 
-        let (line_start_offset, _) = self.positions.get(idx).unwrap();
-        let column = offset - line_start_offset;
+        let (line, byte_col) = self.from_offset(offset);
+        let (line_start, _) = self.positions[line.as_usize()];
 
-        (LineNumber::from(idx as u32), column)
+        let start_idx = self
+            .multibyte_chars
+            .partition_point(|c| (c.byte_pos as usize) < line_start);
+        let end_idx = self
+            .multibyte_chars
+            .partition_point(|c| (c.byte_pos as usize) < offset);
+
+        let extra_bytes: usize = self.multibyte_chars[start_idx..end_idx]
+            .iter()
+            .map(|c| c.bytes as usize - 1)
+            .sum();
+
+        (line, byte_col - extra_bytes)
+    }
+
+    /// Return the line and column corresponding to this `offset`, with
+    /// the column measured in display (visual) width, as used when
+    /// rendering carets and diagnostics aligned under source text.
+    ///
+    /// Zero-width chars (e.g. combining marks) don't advance the
+    /// column, East Asian Wide/Fullwidth chars advance it by 2, `\t`
+    /// advances to the next multiple of `tab_width`, and everything
+    /// else advances it by 1.
+    ///
+    /// If `offset` falls in the middle of a multi-byte char, it is
+    /// rounded down to the start of that char.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` is out of bounds.
+    pub fn from_offset_display(&self, offset: usize, tab_width: usize) -> (LineNumber, usize) {
+        let (line, mut byte_col) = self.from_offset(offset);
+        let entries = &self.non_narrow_chars[line.as_usize()];
+        let (line_start, _) = self.positions[line.as_usize()];
+
+        // If `byte_col` falls in the middle of a multi-byte char
+        // (including any zero-width or wide char), round down to the
+        // start of that char.
+        let containing_idx = self
+            .multibyte_chars
+            .partition_point(|c| (c.byte_pos as usize) <= line_start + byte_col);
+        if let Some(c) = containing_idx.checked_sub(1).map(|i| self.multibyte_chars[i]) {
+            if line_start + byte_col < c.byte_pos as usize + c.bytes as usize {
+                byte_col = c.byte_pos as usize - line_start;
+            }
+        }
+
+        // The chars between two recorded chars are all narrow, so each
+        // occupies one column however many UTF-8 bytes it takes.
+        let narrow_cols = |from: usize, to: usize| -> usize {
+            let multibyte = &self.multibyte_chars;
+            let lo = multibyte.partition_point(|c| (c.byte_pos as usize) < line_start + from);
+            let hi = multibyte.partition_point(|c| (c.byte_pos as usize) < line_start + to);
+            let extra_bytes: usize = multibyte[lo..hi].iter().map(|c| c.bytes as usize - 1).sum();
+            (to - from) - extra_bytes
+        };
+
+        let end_idx = entries.partition_point(|c| (c.byte_pos() as usize) < byte_col);
+
+        let mut display_col = 0;
+        let mut prev_byte_pos = 0;
+        for entry in &entries[..end_idx] {
+            display_col += narrow_cols(prev_byte_pos, entry.byte_pos() as usize);
+            display_col = match entry {
+                NonNarrowChar::ZeroWidth { .. } => display_col,
+                NonNarrowChar::Wide { .. } => display_col + 2,
+                NonNarrowChar::Tab { .. } => (display_col / tab_width + 1) * tab_width,
+            };
+            prev_byte_pos = entry.byte_pos() as usize + entry.len_utf8() as usize;
+        }
+        display_col += narrow_cols(prev_byte_pos, byte_col);
+
+        (line, display_col)
+    }
+
+    /// The absolute byte offset of the start of `line`, or `None` if
+    /// `line` is out of range.
+    pub fn line_start_offset(&self, line: LineNumber) -> Option<usize> {
+        self.positions.get(line.as_usize()).map(|(start, _)| *start)
+    }
+
+    /// The `(start, end)` byte offsets of `line`, or `None` if `line`
+    /// is out of range. `end` excludes the line's terminator.
+    pub fn line_range(&self, line: LineNumber) -> Option<(usize, usize)> {
+        self.positions.get(line.as_usize()).copied()
+    }
+
+    /// The inverse of [`LinePositions::from_offset`]: the absolute
+    /// byte offset of `column` on `line`, or `None` if `line` is out
+    /// of range or `column` is beyond the end of the line.
+    pub fn offset_of(&self, line: LineNumber, column: usize) -> Option<usize> {
+        let (start, end) = self.line_range(line)?;
+        if start + column > end {
+            return None;
+        }
+
+        Some(start + column)
     }
 
     /// Convert this region into line spans. If the region includes a
@@ -227,6 +706,89 @@ impl LinePositions {
 
         res
     }
+
+    /// Create a [`LinePositionsCursor`] for efficient sequential
+    /// (mostly-ascending) access to this `LinePositions`.
+    pub fn cursor(&self) -> LinePositionsCursor<'_> {
+        LinePositionsCursor {
+            line_positions: self,
+            last_line: 0,
+        }
+    }
+}
+
+/// A cursor over a [`LinePositions`] that speeds up repeated
+/// `from_offset`/`from_region` calls whose offsets are mostly
+/// ascending, such as walking a file front-to-back.
+///
+/// Obtained via [`LinePositions::cursor`]. The random-access
+/// `LinePositions` API is unaffected by this and can still be used
+/// directly.
+#[derive(Debug)]
+pub struct LinePositionsCursor<'a> {
+    line_positions: &'a LinePositions,
+    /// The line index resolved by the previous query.
+    last_line: usize,
+}
+
+impl<'a> LinePositionsCursor<'a> {
+    /// Equivalent to [`LinePositions::from_offset`], but checks the
+    /// previously resolved line (and the line after it) before
+    /// falling back to a binary search. For an ascending sequence of
+    /// offsets, this makes the whole scan amortized O(1) per query.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` is out of bounds.
+    pub fn from_offset(&mut self, offset: usize) -> (LineNumber, usize) {
+        let positions = &self.line_positions.positions;
+
+        for &candidate in &[self.last_line, self.last_line + 1] {
+            if let Some((line_start, line_end)) = positions.get(candidate) {
+                if offset >= *line_start && offset <= *line_end {
+                    self.last_line = candidate;
+                    return (LineNumber::from(candidate as u32), offset - line_start);
+                }
+            }
+        }
+
+        let (line, column) = self.line_positions.from_offset(offset);
+        self.last_line = line.as_usize();
+        (line, column)
+    }
+
+    /// Equivalent to [`LinePositions::from_region`], but uses the
+    /// same cached-line lookup as [`LinePositionsCursor::from_offset`].
+    ///
+    /// `region_start` and `region_end` are measured in bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `region_start` or `region_end` are out of bounds, or
+    /// if `region_start` is greater than `region_end`.
+    pub fn from_region(&mut self, region_start: usize, region_end: usize) -> Vec<SingleLineSpan> {
+        assert!(region_start <= region_end);
+
+        let (first_line, _) = self.from_offset(region_start);
+        let (last_line, _) = self.from_offset(region_end);
+
+        let positions = &self.line_positions.positions;
+        let mut res = vec![];
+        for idx in first_line.0..=last_line.0 {
+            let (line_start, line_end) = positions[idx as usize];
+            res.push(SingleLineSpan {
+                line: idx.into(),
+                start_col: region_start.saturating_sub(line_start) as u32,
+                end_col: if region_end < line_end {
+                    region_end - line_start
+                } else {
+                    line_end - line_start
+                } as u32,
+            });
+        }
+
+        res
+    }
 }
 
 #[cfg(test)]
@@ -333,4 +895,279 @@ mod tests {
         let newline_positions: LinePositions = "foo".into();
         let _ = newline_positions.from_offset(4);
     }
+
+    #[test]
+    fn test_crlf_line_endings() {
+        // A mix of \n, \r\n and bare \r line terminators.
+        let s = "foo\r\nbar\nbaz\rquux";
+        let newline_positions: LinePositions = s.into();
+
+        // The \r before a \n should not be treated as part of the
+        // line's content.
+        let (line, column) = newline_positions.from_offset(3);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 3);
+
+        let (line, column) = newline_positions.from_offset(5);
+        assert_eq!(line.as_usize(), 1);
+        assert_eq!(column, 0);
+
+        let (line, column) = newline_positions.from_offset(9);
+        assert_eq!(line.as_usize(), 2);
+        assert_eq!(column, 0);
+
+        // A bare \r also starts a new line.
+        let (line, column) = newline_positions.from_offset(13);
+        assert_eq!(line.as_usize(), 3);
+        assert_eq!(column, 0);
+    }
+
+    #[test]
+    fn test_from_offset_crlf_newline_byte() {
+        // The `\n` of a `\r\n` pair is in bounds, and isn't covered
+        // by either line's content range. It should resolve to the
+        // end of the line it terminates, not panic.
+        let newline_positions: LinePositions = "foo\r\nbar".into();
+        let (line, column) = newline_positions.from_offset(4);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 3);
+    }
+
+    #[test]
+    fn test_from_offset_utf16_ascii() {
+        let newline_positions: LinePositions = "foo\nbar".into();
+        let (line, column) = newline_positions.from_offset_utf16(5);
+        assert_eq!(line.as_usize(), 1);
+        assert_eq!(column, 1);
+    }
+
+    #[test]
+    fn test_from_offset_utf16_bmp() {
+        // "café" has a 2-byte UTF-8 char ('é') that is a single
+        // UTF-16 code unit.
+        let s = "café\nbar";
+        let newline_positions: LinePositions = s.into();
+
+        // Byte offset of the 'é' itself: only ASCII precedes it, so
+        // the byte column and the UTF-16 column are both 3.
+        let (line, column) = newline_positions.from_offset_utf16(3);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 3);
+
+        // End of the first line, after the 2-byte char.
+        let (line, column) = newline_positions.from_offset_utf16(5);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 4);
+    }
+
+    #[test]
+    fn test_from_offset_utf16_astral() {
+        // U+1F600 is encoded as 4 UTF-8 bytes but a UTF-16 surrogate
+        // pair (2 code units).
+        let s = "a\u{1f600}b";
+        let newline_positions: LinePositions = s.into();
+
+        let (line, column) = newline_positions.from_offset_utf16(5);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 3);
+    }
+
+    #[test]
+    fn test_from_offset_chars_ascii() {
+        let newline_positions: LinePositions = "foo\nbar".into();
+        let (line, column) = newline_positions.from_offset_chars(5);
+        assert_eq!(line.as_usize(), 1);
+        assert_eq!(column, 1);
+    }
+
+    #[test]
+    fn test_from_offset_chars_multibyte() {
+        // "é" is one char but two UTF-8 bytes.
+        let s = "café\nbar";
+        let newline_positions: LinePositions = s.into();
+
+        // The byte offset of "é" is 3, but its char column is also 3,
+        // since "c", "a" and "f" are each one byte.
+        let (line, column) = newline_positions.from_offset_chars(3);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 3);
+
+        // The byte offset of the newline is 5, but the line only has
+        // 4 chars.
+        let (line, column) = newline_positions.from_offset_chars(5);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 4);
+    }
+
+    #[test]
+    fn test_from_offset_chars_mid_char_offset() {
+        // U+4E2D ("中") is 3 UTF-8 bytes. An offset inside it should
+        // round down to its start, not underflow.
+        let newline_positions: LinePositions = "中".into();
+        let (line, column) = newline_positions.from_offset_chars(1);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 0);
+
+        let (line, column) = newline_positions.from_offset_chars(2);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 0);
+    }
+
+    #[test]
+    fn test_from_offset_display_tab() {
+        let newline_positions: LinePositions = "a\tb".into();
+        let (line, column) = newline_positions.from_offset_display(2, 4);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 4);
+    }
+
+    #[test]
+    fn test_from_offset_display_wide_char() {
+        // U+4E2D ("中") is a CJK ideograph, so it's 2 columns wide.
+        let s = "a中b";
+        let newline_positions: LinePositions = s.into();
+
+        let offset = s.find('b').unwrap();
+        let (line, column) = newline_positions.from_offset_display(offset, 4);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 3);
+    }
+
+    #[test]
+    fn test_from_offset_display_zero_width() {
+        // U+0301 COMBINING ACUTE ACCENT doesn't occupy a column.
+        let s = "e\u{0301}b";
+        let newline_positions: LinePositions = s.into();
+
+        let offset = s.find('b').unwrap();
+        let (line, column) = newline_positions.from_offset_display(offset, 4);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 1);
+    }
+
+    #[test]
+    fn test_from_offset_display_mid_char_offset() {
+        // U+4E2D ("中") is 3 UTF-8 bytes and 2 display columns. An
+        // offset inside it should round down to its start, not
+        // underflow.
+        let newline_positions: LinePositions = "中b".into();
+        let (line, column) = newline_positions.from_offset_display(1, 4);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 0);
+
+        let (line, column) = newline_positions.from_offset_display(2, 4);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 0);
+    }
+
+    #[test]
+    fn test_offset_of() {
+        let newline_positions: LinePositions = "foo\nbar".into();
+        assert_eq!(newline_positions.offset_of(1.into(), 1), Some(5));
+    }
+
+    #[test]
+    fn test_offset_of_out_of_range_line() {
+        let newline_positions: LinePositions = "foo\nbar".into();
+        assert_eq!(newline_positions.offset_of(2.into(), 0), None);
+    }
+
+    #[test]
+    fn test_offset_of_column_beyond_line() {
+        let newline_positions: LinePositions = "foo\nbar".into();
+        assert_eq!(newline_positions.offset_of(0.into(), 10), None);
+    }
+
+    #[test]
+    fn test_line_start_offset() {
+        let newline_positions: LinePositions = "foo\nbar".into();
+        assert_eq!(newline_positions.line_start_offset(1.into()), Some(4));
+        assert_eq!(newline_positions.line_start_offset(2.into()), None);
+    }
+
+    #[test]
+    fn test_line_range() {
+        let newline_positions: LinePositions = "foo\nbar".into();
+        assert_eq!(newline_positions.line_range(0.into()), Some((0, 3)));
+        assert_eq!(newline_positions.line_range(2.into()), None);
+    }
+
+    #[test]
+    fn test_cursor_ascending_scan() {
+        let newline_positions: LinePositions = "foo\nbar\nbaz".into();
+        let mut cursor = newline_positions.cursor();
+
+        let (line, column) = cursor.from_offset(1);
+        assert_eq!(line.as_usize(), 0);
+        assert_eq!(column, 1);
+
+        let (line, column) = cursor.from_offset(5);
+        assert_eq!(line.as_usize(), 1);
+        assert_eq!(column, 1);
+
+        let (line, column) = cursor.from_offset(10);
+        assert_eq!(line.as_usize(), 2);
+        assert_eq!(column, 2);
+    }
+
+    #[test]
+    fn test_cursor_matches_random_access() {
+        let newline_positions: LinePositions = "foo\nbar\nbaz".into();
+        let mut cursor = newline_positions.cursor();
+
+        for offset in [10, 0, 7, 4] {
+            assert_eq!(
+                cursor.from_offset(offset),
+                newline_positions.from_offset(offset)
+            );
+        }
+    }
+
+    #[test]
+    fn test_cursor_from_region_matches_random_access() {
+        let newline_positions: LinePositions = "foo\nbar\nbaz".into();
+        let mut cursor = newline_positions.cursor();
+
+        for (region_start, region_end) in [(0, 2), (4, 6), (8, 10)] {
+            assert_eq!(
+                cursor.from_region(region_start, region_end),
+                newline_positions.from_region(region_start, region_end)
+            );
+        }
+    }
+
+    #[test]
+    fn test_builder_matches_from_str() {
+        let s = "foo\r\nbar\nbaz";
+
+        let mut builder = LinePositionsBuilder::new();
+        builder.push_str("foo\r");
+        builder.push_str("\nbar\n");
+        builder.push_str("baz");
+        let built: LinePositions = builder.finish();
+
+        let expected: LinePositions = s.into();
+        assert_eq!(built.positions, expected.positions);
+    }
+
+    #[test]
+    fn test_builder_empty() {
+        let built = LinePositionsBuilder::new().finish();
+        assert_eq!(built.from_offset(0), (0.into(), 0));
+    }
+
+    #[test]
+    fn test_builder_empty_chunk_across_crlf() {
+        // An empty `push_str` call must not lose track of a `\r` at
+        // the end of the buffer that might still turn out to be part
+        // of a `\r\n` pair.
+        let mut builder = LinePositionsBuilder::new();
+        builder.push_str("foo\r");
+        builder.push_str("");
+        builder.push_str("\nbar\n");
+        let built = builder.finish();
+
+        let expected: LinePositions = "foo\r\nbar\n".into();
+        assert_eq!(built.positions, expected.positions);
+    }
 }